@@ -1,130 +1,419 @@
 use std::collections::HashSet;
-use std::collections::HashMap;
 use std::hash::Hash;
 
-struct Scope<T> {
-    name_map: HashMap<T, String>,
-    used: HashSet<String>,
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// C keeps several identifier spellings from colliding with each other by giving them
+/// disjoint namespaces: a `struct foo` and a variable `foo` are perfectly legal side by side.
+/// Rust has an analogous (if not identical) split between its type and value namespaces, plus
+/// a separate namespace for loop/block labels, so `Renamer` tracks collisions per-namespace
+/// instead of forcing every C identifier through one flat name space.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Namespace {
+    /// Variables, functions, and typedef names.
+    Ordinary,
+    /// struct/union/enum tags.
+    Tag,
+    /// struct/union member names.
+    Member,
+    /// Statement labels (`goto` targets).
+    Label,
 }
 
-impl<T: Clone + Eq + Hash> Scope<T> {
-    pub fn new() -> Self {
-        Self::new_with_reserved(HashSet::new())
-    }
+const NAMESPACE_COUNT: usize = 4;
 
-    pub fn new_with_reserved(reserved: HashSet<String>) -> Self {
-        Scope {
-            name_map: HashMap::new(),
-            used: reserved,
+impl Namespace {
+    fn index(self) -> usize {
+        match self {
+            Namespace::Ordinary => 0,
+            Namespace::Tag => 1,
+            Namespace::Member => 2,
+            Namespace::Label => 3,
         }
     }
+}
 
-    pub fn contains_key(&self, key: &T) -> bool {
-        self.name_map.contains_key(key)
+/// Stable handle for a scope stored in the `Renamer`'s arena. `ScopeId`s remain valid even
+/// after the scope they identify stops being the active one, so a scope can be re-entered
+/// later without recreating it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ScopeId(usize);
+
+/// Stable handle for a single `insert`ed binding, independent of whatever scope is currently
+/// active. Unlike a mangled name, a `BindingId` stays meaningful even if the same spelling is
+/// later reused in an unrelated branch of the scope tree.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BindingId(usize);
+
+struct Binding<T> {
+    key: T,
+    basename: String,
+    name: String,
+    scope: ScopeId,
+}
+
+/// Callback fired when `pick_name` has to append a disambiguating suffix: reports the
+/// original basename, the mangled name it was given instead, and the scope that held the
+/// conflicting reservation.
+type OnRename = dyn FnMut(&str, &str, ScopeId);
+
+struct Scope<T> {
+    name_map: [FxHashMap<T, BindingId>; NAMESPACE_COUNT],
+    used: [FxHashSet<String>; NAMESPACE_COUNT],
+    parent: Option<ScopeId>,
+}
+
+impl<T: Clone + Eq + Hash> Scope<T> {
+    pub fn new(parent: Option<ScopeId>) -> Self {
+        Self::new_with_reserved(FxHashSet::default(), parent)
     }
 
-    pub fn contains_value(&self, val: &str) -> bool {
-        self.used.contains(val)
+    /// `reserved` is excluded from every namespace: it exists to keep Rust keywords out of
+    /// generated identifiers, and a keyword is unavailable no matter what kind of C name it
+    /// stands in for.
+    pub fn new_with_reserved(reserved: FxHashSet<String>, parent: Option<ScopeId>) -> Self {
+        Scope {
+            name_map: [(); NAMESPACE_COUNT].map(|_| FxHashMap::default()),
+            used: [(); NAMESPACE_COUNT].map(|_| reserved.clone()),
+            parent,
+        }
     }
 
-    pub fn reserve(&mut self, val: String) {
-        self.used.insert(val);
+    pub fn contains_key(&self, ns: Namespace, key: &T) -> bool {
+        self.name_map[ns.index()].contains_key(key)
     }
 }
 
+/// Renames of source-level names (of type `T`) into fresh Rust identifiers.
+///
+/// Scopes are stored in an arena rather than a LIFO stack: `add_scope` allocates a new
+/// `Scope` whose parent is whatever scope is currently active and hands back a stable
+/// `ScopeId`, while `enter_scope`/`exit_scope` change which scope is active without
+/// destroying anything. This lets a translator stash the `ScopeId` that was active while it
+/// was walking some C AST node (e.g. a block that is also the target of a forward `goto`)
+/// and come back to it later with `enter_scope` to resolve names the same way it would have
+/// the first time through.
+///
+/// Picking a fresh name used to mean scanning every live scope's `used` set on every probed
+/// suffix, which makes mangling N clashing names in one translation unit quadratic. Instead,
+/// `Renamer` keeps a `reserved` map per namespace that aggregates every name reserved along
+/// the active scope chain, so checking whether a name is taken is a single lookup. Entries
+/// are refcounted rather than a plain set: two scopes on the same chain can legitimately
+/// reserve the same literal name (e.g. both blocking the same Rust keyword), and a name must
+/// stay reserved as long as any of them is still on the chain. A `suffix_hint` per namespace
+/// remembers the next untried suffix for each basename so `pick_name` doesn't re-probe
+/// suffixes that were already claimed by an earlier call.
 pub struct Renamer<T> {
     scopes: Vec<Scope<T>>,
+    active: ScopeId,
     next_fresh: u64,
+    reserved: [FxHashMap<String, u32>; NAMESPACE_COUNT],
+    suffix_hint: [FxHashMap<String, u64>; NAMESPACE_COUNT],
+    bindings: Vec<Binding<T>>,
+    by_name: FxHashMap<String, BindingId>,
+    on_rename: Option<Box<OnRename>>,
 }
 
 impl<T: Clone + Eq + Hash> Renamer<T> {
 
-    /// Creates a new renaming environment with a single, empty scope. The given set of
+    /// Creates a new renaming environment with a single, empty root scope. The given set of
     /// reserved names will exclude those names from being chosen as the mangled names from
-    /// the insert method.
+    /// the insert method, in every namespace.
     pub fn new(reserved_names: HashSet<String>) -> Self {
-        Renamer {
-            scopes: vec![Scope::new_with_reserved(reserved_names)],
+        let reserved_names: FxHashSet<String> = reserved_names.into_iter().collect();
+        let root = Scope::new_with_reserved(reserved_names, None);
+        let mut renamer = Renamer {
+            scopes: vec![root],
+            active: ScopeId(0),
             next_fresh: 0,
+            reserved: [(); NAMESPACE_COUNT].map(|_| FxHashMap::default()),
+            suffix_hint: [(); NAMESPACE_COUNT].map(|_| FxHashMap::default()),
+            bindings: Vec::new(),
+            by_name: FxHashMap::default(),
+            on_rename: None,
+        };
+        renamer.rebuild_reserved();
+        renamer
+    }
+
+    /// Registers a callback that fires whenever `pick_name` has to append a disambiguating
+    /// suffix, reporting the basename that was asked for, the mangled name it was given
+    /// instead, and the `ScopeId` of the nearest scope on the active chain (possibly the
+    /// current scope itself) that had already reserved the basename. Translators can
+    /// use this to surface a warning or record provenance instead of silently losing the fact
+    /// that a rename happened.
+    pub fn set_on_rename(&mut self, callback: impl FnMut(&str, &str, ScopeId) + 'static) {
+        self.on_rename = Some(Box::new(callback));
+    }
+
+    /// Introduces a new name binding scope whose parent is the currently active scope, and
+    /// makes it the active scope. Returns a `ScopeId` that can be used to re-enter this scope
+    /// later with `enter_scope`, even after some other scope becomes active.
+    pub fn add_scope(&mut self) -> ScopeId {
+        let id = ScopeId(self.scopes.len());
+        self.scopes.push(Scope::new(Some(self.active)));
+        self.active = id;
+        id
+    }
+
+    /// Makes an already-created scope active again without recreating it, returning the
+    /// `ScopeId` that was active just before the jump.
+    ///
+    /// This jumps to a scope that may not share a prefix with the currently active chain (e.g.
+    /// re-entering the scope of a block that is the target of a forward `goto`, which has no
+    /// structural relationship to the scope the `goto` itself sits in), so the `reserved`
+    /// aggregate is rebuilt from scratch for the new chain rather than patched incrementally,
+    /// and the suffix hints are reset since names reserved on the old chain may now be free.
+    /// Because of that, restoring the scope that was active before the jump means calling
+    /// `enter_scope` again with the `ScopeId` this returned: `exit_scope` always walks to the
+    /// entered scope's structural *parent*, which is not necessarily where the caller came
+    /// from.
+    pub fn enter_scope(&mut self, id: ScopeId) -> ScopeId {
+        let previous = self.active;
+        self.active = id;
+        self.rebuild_reserved();
+        for ns_idx in 0..NAMESPACE_COUNT {
+            self.suffix_hint[ns_idx].clear();
         }
+        previous
     }
 
-    /// Introduces a new name binding scope
-    pub fn add_scope(&mut self) {
-        self.scopes.push(Scope::new())
+    /// Makes the parent of the currently active scope active again, unreserving any names
+    /// that only the exited scope had claimed.
+    pub fn exit_scope(&mut self) {
+        let exited = self.active;
+        let parent = self.scopes[exited.0].parent
+            .expect("Attempting to exit the root scope");
+
+        for ns_idx in 0..NAMESPACE_COUNT {
+            let freed: Vec<String> = self.scopes[exited.0].used[ns_idx].iter().cloned().collect();
+            for name in freed {
+                self.unreserve_name(ns_idx, &name);
+                self.lower_suffix_hint(ns_idx, &name);
+            }
+        }
+
+        self.active = parent;
     }
 
-    /// Drops the current name binding scope
-    pub fn drop_scope(&mut self) {
-        if self.scopes.len() == 1 {
-            panic!("Attempting to drop outermost scope")
+    /// Drops one reservation of `name` in the given namespace. `reserved` is refcounted
+    /// rather than a plain set, so this only stops treating the name as taken once no scope
+    /// still reserving it remains — an ancestor scope further up the (still active) chain may
+    /// have reserved the exact same literal name independently.
+    fn unreserve_name(&mut self, ns_idx: usize, name: &str) {
+        if let Some(count) = self.reserved[ns_idx].get_mut(name) {
+            *count -= 1;
+            if *count == 0 {
+                self.reserved[ns_idx].remove(name);
+            }
         }
+    }
+
+    /// Called by `exit_scope` when `name` stops being reserved: if it's of the form `{basename}_{i}` that
+    /// `pick_name` could have produced, lowers `suffix_hint[ns]` for `basename` back down to
+    /// `i` so a later `pick_name` call notices the freed suffix instead of skipping past it.
+    fn lower_suffix_hint(&mut self, ns_idx: usize, name: &str) {
+        let Some((basename, suffix)) = name.rsplit_once('_') else { return };
+        let Ok(i) = suffix.parse::<u64>() else { return };
+
+        if let Some(hint) = self.suffix_hint[ns_idx].get_mut(basename) {
+            *hint = (*hint).min(i);
+        }
+    }
 
-        self.scopes.pop();
+    /// Drops the current name binding scope, making its parent active. This is the same as
+    /// `exit_scope`: the scope stays in the arena, so a `ScopeId` obtained before this call
+    /// can still be passed to `enter_scope` or `resolve_in_scope` afterwards.
+    pub fn drop_scope(&mut self) {
+        self.exit_scope()
     }
 
     fn current_scope(&self) -> &Scope<T> {
-        self.scopes.last().expect("Expected a scope")
+        &self.scopes[self.active.0]
     }
 
     fn current_scope_mut(&mut self) -> &mut Scope<T> {
-        self.scopes.last_mut().expect("Expected a scope")
+        &mut self.scopes[self.active.0]
     }
 
-    /// Is the mangled name currently in use
-    fn is_target_used(&self, key: &str) -> bool {
-        let key = key.to_string();
-
-        self.scopes.iter().any(|x| x.contains_value(&key))
+    /// Walks the chain of scopes starting at `id` and following `parent` links up to the
+    /// root, mirroring rust-analyzer's `ExprScopes::scope_chain`.
+    pub fn scope_chain(&self, id: ScopeId) -> impl Iterator<Item = ScopeId> + '_ {
+        std::iter::successors(Some(id), move |&id| self.scopes[id.0].parent)
     }
 
-    fn pick_name (&mut self, basename: &str) -> String {
+    /// Rebuilds the `reserved` aggregate from every scope on the active chain, re-deriving
+    /// each name's refcount from scratch. Only needed when the active chain changes in a way
+    /// that isn't a simple push/pop, i.e. `enter_scope`.
+    fn rebuild_reserved(&mut self) {
+        for ns_idx in 0..NAMESPACE_COUNT {
+            self.reserved[ns_idx].clear();
+        }
 
-        let mut target = basename.to_string();
-        for i in 0.. {
-            if self.is_target_used(&target) {
-                target = format!("{}_{}", basename, i);
-            } else {
-                break
+        let chain: Vec<ScopeId> = self.scope_chain(self.active).collect();
+        for id in chain {
+            for ns_idx in 0..NAMESPACE_COUNT {
+                for name in &self.scopes[id.0].used[ns_idx] {
+                    *self.reserved[ns_idx].entry(name.clone()).or_insert(0) += 1;
+                }
             }
         }
+    }
+
+    /// Is the mangled name currently in use within the given namespace
+    fn is_target_used(&self, ns: Namespace, key: &str) -> bool {
+        self.reserved[ns.index()].contains_key(key)
+    }
 
-        self.current_scope_mut().reserve(target.clone());
+    /// Reserves `name` in the given namespace: marks it used in the current scope and, if the
+    /// current scope didn't already claim it, bumps its refcount in the aggregated `reserved`
+    /// map so later lookups see it in O(1). The refcount tracks how many scopes on a chain
+    /// have `name` in their `used` set, so a redundant reservation within the same scope must
+    /// not inflate it, or `exit_scope` would never bring the count back down to zero.
+    fn reserve_name(&mut self, ns: Namespace, name: String) {
+        let newly_used = self.current_scope_mut().used[ns.index()].insert(name.clone());
+        if newly_used {
+            *self.reserved[ns.index()].entry(name).or_insert(0) += 1;
+        }
+    }
 
-        target
+    /// Finds the nearest scope on the active chain that has already reserved `name` in the
+    /// given namespace, i.e. the scope responsible for forcing a rename away from `name`.
+    fn conflicting_scope(&self, ns: Namespace, name: &str) -> Option<ScopeId> {
+        self.scope_chain(self.active)
+            .find(|&id| self.scopes[id.0].used[ns.index()].contains(name))
     }
 
-    /// Introduce a new name binding into the current scope. If the key is unbound in
-    /// the current scope then Some of the resulting mangled name is returned, otherwise
-    /// None.
-    pub fn insert(&mut self, key: T, basename: &str) -> Option<String> {
+    fn pick_name(&mut self, ns: Namespace, basename: &str) -> String {
+        if !self.is_target_used(ns, basename) {
+            self.reserve_name(ns, basename.to_string());
+            return basename.to_string();
+        }
+
+        let conflict = self.conflicting_scope(ns, basename);
+
+        let mut i = *self.suffix_hint[ns.index()].get(basename).unwrap_or(&0);
+        loop {
+            let candidate = format!("{}_{}", basename, i);
+            if !self.is_target_used(ns, &candidate) {
+                self.suffix_hint[ns.index()].insert(basename.to_string(), i + 1);
+                self.reserve_name(ns, candidate.clone());
+
+                if let (Some(on_rename), Some(conflict)) = (self.on_rename.as_mut(), conflict) {
+                    on_rename(basename, &candidate, conflict);
+                }
 
-        if self.current_scope().contains_key(&key) {
+                return candidate;
+            }
+            i += 1;
+        }
+    }
+
+    /// Reserve a name in the given namespace of the current scope without binding it to any
+    /// key, so it won't be picked as a fresh mangled name later on.
+    pub fn reserve(&mut self, ns: Namespace, name: String) {
+        self.reserve_name(ns, name);
+    }
+
+    /// Introduce a new name binding into the current scope's given namespace. If the key is
+    /// unbound in the current scope's namespace then Some of the resulting mangled name is
+    /// returned, otherwise None.
+    pub fn insert(&mut self, ns: Namespace, key: T, basename: &str) -> Option<String> {
+
+        if self.current_scope().contains_key(ns, &key) {
             return None
         }
 
-        let target = self.pick_name(basename);
+        let target = self.pick_name(ns, basename);
+        let scope = self.active;
+
+        let id = BindingId(self.bindings.len());
+        self.bindings.push(Binding {
+            key: key.clone(),
+            basename: basename.to_string(),
+            name: target.clone(),
+            scope,
+        });
+        self.by_name.insert(target.clone(), id);
 
-        self.current_scope_mut().name_map.insert(key, target.clone());
+        self.current_scope_mut().name_map[ns.index()].insert(key, id);
 
         Some(target)
     }
 
-    /// Lookup the given key in all of the scopes returning Some of the matched mangled name
-    /// if one exists, otherwise None.
-    pub fn get(&self, key: &T) -> Option<String> {
-        for scope in self.scopes.iter().rev() {
-            if let Some(target) = scope.name_map.get(key) {
-                return Some(target.to_string())
+    /// Lookup the given key in the given namespace along the active scope chain, returning
+    /// Some of the matched mangled name if one exists, otherwise None.
+    pub fn get(&self, ns: Namespace, key: &T) -> Option<String> {
+        self.binding_id_of(ns, key).map(|id| self.bindings[id.0].name.clone())
+    }
+
+    /// Look up the `BindingId` for `key` in the given namespace along the active scope chain,
+    /// independent of whatever mangled name was ultimately chosen for it.
+    pub fn binding_id_of(&self, ns: Namespace, key: &T) -> Option<BindingId> {
+        for id in self.scope_chain(self.active) {
+            if let Some(&bid) = self.scopes[id.0].name_map[ns.index()].get(key) {
+                return Some(bid)
+            }
+        }
+        None
+    }
+
+    /// Reverse lookup: given a mangled name that `insert` produced, find the `BindingId` it
+    /// was assigned. If the same spelling was reused by bindings in unrelated branches of the
+    /// scope tree, this returns whichever one was inserted most recently.
+    pub fn resolve(&self, mangled: &str) -> Option<BindingId> {
+        self.by_name.get(mangled).copied()
+    }
+
+    /// Full detail for a `BindingId`: the original key, the mangled name it was given, and the
+    /// scope it was inserted into.
+    pub fn binding(&self, id: BindingId) -> (&T, &str, ScopeId) {
+        let binding = &self.bindings[id.0];
+        (&binding.key, &binding.name, binding.scope)
+    }
+
+    /// Like `get`, but resolves `key` by walking the chain that starts at `scope` instead of
+    /// the currently active one, so a binding can be looked up without making its scope active.
+    pub fn resolve_in_scope(&self, ns: Namespace, scope: ScopeId, key: &T) -> Option<String> {
+        for id in self.scope_chain(scope) {
+            if let Some(&bid) = self.scopes[id.0].name_map[ns.index()].get(key) {
+                return Some(self.bindings[bid.0].name.clone())
             }
         }
         None
     }
 
-    pub fn fresh(&mut self) -> String {
+    /// Every outer-scope binding, in the given namespace, that `key`'s own binding shadows:
+    /// bindings further up the scope chain that were inserted with the same basename as
+    /// `key`'s own binding. Returns pairs of the shadowed binding's scope and the mangled
+    /// name it actually ended up with, which may carry a suffix.
+    ///
+    /// A scope can hold more than one binding with the same basename in the same namespace
+    /// (e.g. two distinct anonymous members both based on "anon"), so when several match,
+    /// the most recently inserted one is reported rather than whatever a `HashMap` happens
+    /// to iterate first.
+    pub fn shadows(&self, ns: Namespace, key: &T) -> Vec<(ScopeId, String)> {
+        let own_id = match self.binding_id_of(ns, key) {
+            Some(id) => id,
+            None => return Vec::new(),
+        };
+        let own = &self.bindings[own_id.0];
+
+        self.scope_chain(own.scope)
+            .skip(1)
+            .filter_map(|id| {
+                self.scopes[id.0].name_map[ns.index()].values()
+                    .filter(|&&bid| self.bindings[bid.0].basename == own.basename)
+                    .max_by_key(|&&bid| bid.0)
+                    .map(|&bid| (id, self.bindings[bid.0].name.clone()))
+            })
+            .collect()
+    }
+
+    pub fn fresh(&mut self, ns: Namespace) -> String {
         let fresh = self.next_fresh;
         self.next_fresh += 1;
-        self.pick_name(&format!("fresh{}", fresh))
+        self.pick_name(ns, &format!("fresh{}", fresh))
     }
 }
 
@@ -137,12 +426,12 @@ mod tests {
         let keywords = vec!["reserved"].into_iter().map(str::to_string).collect();
         let mut renamer = Renamer::new(keywords);
 
-        let one1 = renamer.insert(1,"one").unwrap();
-        let one2 = renamer.get(&1).unwrap();
+        let one1 = renamer.insert(Namespace::Ordinary, 1, "one").unwrap();
+        let one2 = renamer.get(Namespace::Ordinary, &1).unwrap();
         assert_eq!(one1, one2);
 
-        let reserved1 = renamer.insert(2, "reserved").unwrap();
-        let reserved2 = renamer.get(&2).unwrap();
+        let reserved1 = renamer.insert(Namespace::Ordinary, 2, "reserved").unwrap();
+        let reserved2 = renamer.get(Namespace::Ordinary, &2).unwrap();
         assert_eq!(reserved1, "reserved_0");
         assert_eq!(reserved2, "reserved_0");
     }
@@ -151,30 +440,207 @@ mod tests {
     fn scoped() {
         let mut renamer = Renamer::new(HashSet::new());
 
-        let one1 = renamer.insert(10, "one").unwrap();
+        let one1 = renamer.insert(Namespace::Ordinary, 10, "one").unwrap();
         renamer.add_scope();
 
-        let one2 = renamer.get(&10).unwrap();
+        let one2 = renamer.get(Namespace::Ordinary, &10).unwrap();
         assert_eq!(one1, one2);
 
-        let one3 = renamer.insert(20,"one").unwrap();
-        let one4 = renamer.get(&20).unwrap();
+        let one3 = renamer.insert(Namespace::Ordinary, 20, "one").unwrap();
+        let one4 = renamer.get(Namespace::Ordinary, &20).unwrap();
         assert_eq!(one3, one4);
         assert_ne!(one3, one2);
 
         renamer.drop_scope();
 
-        let one5 = renamer.get(&10).unwrap();
+        let one5 = renamer.get(Namespace::Ordinary, &10).unwrap();
         assert_eq!(one5, one2);
     }
 
     #[test]
     fn forgets() {
         let mut renamer = Renamer::new(HashSet::new());
-        assert_eq!(renamer.get(&1), None);
+        assert_eq!(renamer.get(Namespace::Ordinary, &1), None);
         renamer.add_scope();
-        renamer.insert(1,"example");
+        renamer.insert(Namespace::Ordinary, 1, "example");
         renamer.drop_scope();
-        assert_eq!(renamer.get(&1), None);
+        assert_eq!(renamer.get(Namespace::Ordinary, &1), None);
+    }
+
+    #[test]
+    fn reenter_scope() {
+        let mut renamer = Renamer::new(HashSet::new());
+
+        let block = renamer.add_scope();
+        let inner = renamer.insert(Namespace::Ordinary, 1, "x").unwrap();
+        renamer.drop_scope();
+
+        assert_eq!(renamer.get(Namespace::Ordinary, &1), None);
+
+        renamer.enter_scope(block);
+        assert_eq!(renamer.get(Namespace::Ordinary, &1), Some(inner));
+        renamer.exit_scope();
+
+        assert_eq!(renamer.get(Namespace::Ordinary, &1), None);
+    }
+
+    #[test]
+    fn enter_scope_is_restored_via_its_own_return_value_not_exit_scope() {
+        let mut renamer: Renamer<u32> = Renamer::new(HashSet::new());
+
+        let sibling_a = renamer.add_scope();
+        renamer.exit_scope();
+
+        let sibling_b = renamer.add_scope();
+        renamer.insert(Namespace::Ordinary, 1, "x");
+
+        // `sibling_a` is not an ancestor of `sibling_b`, so jumping to it with `enter_scope`
+        // (e.g. to resolve a forward `goto` target) cannot be undone by `exit_scope`, which
+        // would walk to `sibling_a`'s structural parent (the root) instead of back to
+        // `sibling_b`. The caller must restore via the returned `ScopeId` instead.
+        let previous = renamer.enter_scope(sibling_a);
+        assert_eq!(previous, sibling_b);
+        assert_eq!(renamer.get(Namespace::Ordinary, &1), None);
+
+        renamer.enter_scope(previous);
+        assert_eq!(renamer.get(Namespace::Ordinary, &1), Some("x".to_string()));
+    }
+
+    #[test]
+    fn reserve_in_ancestor_scope_survives_child_scope_exit() {
+        let mut renamer: Renamer<u32> = Renamer::new(HashSet::new());
+
+        renamer.reserve(Namespace::Ordinary, "foo".to_string());
+        renamer.add_scope();
+        renamer.reserve(Namespace::Ordinary, "foo".to_string());
+        renamer.exit_scope();
+
+        // The root scope never gave up its own reservation of "foo", so it must still be
+        // unavailable even though the child scope that also reserved it has exited.
+        let name = renamer.insert(Namespace::Ordinary, 1, "foo").unwrap();
+        assert_eq!(name, "foo_0");
+    }
+
+    #[test]
+    fn separate_namespaces() {
+        let mut renamer: Renamer<&str> = Renamer::new(HashSet::new());
+
+        // A struct tag and an ordinary identifier can share a spelling without colliding,
+        // just as they do in C.
+        let tag = renamer.insert(Namespace::Tag, "foo", "foo").unwrap();
+        let value = renamer.insert(Namespace::Ordinary, "foo", "foo").unwrap();
+
+        assert_eq!(tag, "foo");
+        assert_eq!(value, "foo");
+    }
+
+    #[test]
+    fn suffix_hint_skips_claimed_suffixes() {
+        let mut renamer: Renamer<u32> = Renamer::new(HashSet::new());
+
+        let a = renamer.insert(Namespace::Ordinary, 1, "x").unwrap();
+        let b = renamer.insert(Namespace::Ordinary, 2, "x").unwrap();
+        let c = renamer.insert(Namespace::Ordinary, 3, "x").unwrap();
+
+        assert_eq!(a, "x");
+        assert_eq!(b, "x_0");
+        assert_eq!(c, "x_1");
+    }
+
+    #[test]
+    fn suffix_hint_lowers_when_a_scope_frees_a_clashing_name() {
+        let mut renamer: Renamer<u32> = Renamer::new(HashSet::new());
+
+        renamer.add_scope();
+        renamer.insert(Namespace::Ordinary, 1, "x");
+        let b = renamer.insert(Namespace::Ordinary, 2, "x").unwrap();
+        assert_eq!(b, "x_0");
+        renamer.drop_scope();
+
+        // Both "x" and "x_0" are free again, so a fresh sibling scope should be able to
+        // reuse "x_0" instead of climbing straight to "x_1".
+        renamer.add_scope();
+        let c = renamer.insert(Namespace::Ordinary, 3, "x").unwrap();
+        let d = renamer.insert(Namespace::Ordinary, 4, "x").unwrap();
+        assert_eq!(c, "x");
+        assert_eq!(d, "x_0");
+    }
+
+    #[test]
+    fn reverse_lookup_by_mangled_name() {
+        let mut renamer: Renamer<u32> = Renamer::new(HashSet::new());
+
+        let name = renamer.insert(Namespace::Ordinary, 42, "x").unwrap();
+        let id = renamer.binding_id_of(Namespace::Ordinary, &42).unwrap();
+
+        assert_eq!(renamer.resolve(&name), Some(id));
+
+        let (key, mangled, scope) = renamer.binding(id);
+        assert_eq!(*key, 42);
+        assert_eq!(mangled, name);
+        assert_eq!(scope, ScopeId(0));
+    }
+
+    #[test]
+    fn shadows_outer_binding_with_same_basename() {
+        let mut renamer: Renamer<u32> = Renamer::new(HashSet::new());
+
+        let outer = renamer.insert(Namespace::Ordinary, 1, "x").unwrap();
+        renamer.add_scope();
+        let inner = renamer.insert(Namespace::Ordinary, 2, "x").unwrap();
+
+        let shadowed = renamer.shadows(Namespace::Ordinary, &2);
+        assert_eq!(shadowed, vec![(ScopeId(0), outer.clone())]);
+        assert_ne!(outer, inner);
+    }
+
+    #[test]
+    fn shadows_picks_most_recently_inserted_when_basename_repeats() {
+        let mut renamer: Renamer<u32> = Renamer::new(HashSet::new());
+
+        // Two distinct outer-scope bindings sharing basename "x" (legal: `insert` only
+        // dedupes by key, not basename).
+        renamer.insert(Namespace::Ordinary, 1, "x").unwrap();
+        let second = renamer.insert(Namespace::Ordinary, 2, "x").unwrap();
+
+        renamer.add_scope();
+        renamer.insert(Namespace::Ordinary, 3, "x");
+
+        let shadowed = renamer.shadows(Namespace::Ordinary, &3);
+        assert_eq!(shadowed, vec![(ScopeId(0), second)]);
+    }
+
+    #[test]
+    fn resolve_in_scope_does_not_require_entering() {
+        let mut renamer: Renamer<u32> = Renamer::new(HashSet::new());
+
+        let block = renamer.add_scope();
+        let inner = renamer.insert(Namespace::Ordinary, 1, "x").unwrap();
+        renamer.drop_scope();
+
+        assert_eq!(renamer.get(Namespace::Ordinary, &1), None);
+        assert_eq!(
+            renamer.resolve_in_scope(Namespace::Ordinary, block, &1),
+            Some(inner),
+        );
+    }
+
+    #[test]
+    fn on_rename_reports_the_conflicting_scope() {
+        let mut renamer: Renamer<u32> = Renamer::new(HashSet::new());
+        renamer.insert(Namespace::Ordinary, 1, "x").unwrap();
+
+        let reports = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let reports_clone = reports.clone();
+        renamer.set_on_rename(move |basename, chosen, scope| {
+            reports_clone.borrow_mut().push((basename.to_string(), chosen.to_string(), scope));
+        });
+
+        renamer.insert(Namespace::Ordinary, 2, "x").unwrap();
+
+        assert_eq!(
+            *reports.borrow(),
+            vec![("x".to_string(), "x_0".to_string(), ScopeId(0))],
+        );
     }
 }